@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// One named backup job as it appears in a `--config` TOML file, written as `[[job]]` tables.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Job {
+    pub name: String,
+    pub source_dir: String,
+    pub target_dir: String,
+    #[serde(default)]
+    pub versioned: bool,
+    #[serde(default)]
+    pub no_exclude_caches: bool,
+    pub exclude_override: Option<String>,
+    pub pass_args: Option<String>,
+    #[serde(default)]
+    pub prune: bool,
+    #[serde(default)]
+    pub keep_last: usize,
+    #[serde(default)]
+    pub keep_daily: usize,
+    #[serde(default)]
+    pub keep_weekly: usize,
+    #[serde(default)]
+    pub keep_monthly: usize,
+    #[serde(default)]
+    pub keep_yearly: usize,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct JobsFile {
+    #[serde(rename = "job", default)]
+    jobs: Vec<Job>,
+}
+
+/// Parse a `--config` TOML file into its list of jobs.
+pub fn load_jobs(path: &Path) -> Result<Vec<Job>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("reading config file {}", path.display()))?;
+    let file: JobsFile = toml::from_str(&contents)
+        .with_context(|| format!("parsing config file {}", path.display()))?;
+    Ok(file.jobs)
+}
@@ -5,9 +5,9 @@ use std::{
     fs::{read_dir, DirEntry},
     path::{Path, },
     str,
+    time::Duration,
 };
 
-
 use anyhow::{Result, ensure, format_err};
 use chrono::prelude::*;
 use tokio::io::{BufReader, AsyncBufReadExt};
@@ -16,15 +16,84 @@ use tokio::process::Command;
 
 
 
-use clap::Parser;
+use clap::{Parser, Subcommand, Args};
+
+mod retention;
+use retention::{prune_snapshots, RetentionPolicy};
+
+mod stats;
+use stats::{BackupStats, RunMetadata};
+
+mod config;
+use config::{load_jobs, Job};
+
+mod progress;
+use progress::ProgressEvent;
+
+/// How to render the per-file progress events parsed out of rsync's `-P` output.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+enum ProgressFormat {
+    /// A single updating status line, human-readable.
+    #[default]
+    Human,
+    /// One JSON object per progress event, one per line, suitable for piping to a UI.
+    Json,
+    /// Suppress per-file chatter; only the final summary is printed.
+    Quiet,
+}
+
+/// Render a single classified progress event according to `format`.
+fn render_progress_event(event: &ProgressEvent, format: ProgressFormat) -> Result<()> {
+    use std::io::Write;
+
+    match format {
+        ProgressFormat::Json => {
+            println!("{}", serde_json::to_string(event)?);
+        }
+        ProgressFormat::Human => match event {
+            ProgressEvent::FileStarted { name } => println!("{name}"),
+            ProgressEvent::Progress(p) => {
+                print!("\r{:>14} bytes  {:>3}%  {:>8.2} MB/s  ETA {:<10}",
+                    p.bytes, p.percent, p.rate_bytes_per_sec / (1024.0 * 1024.0), p.eta);
+                std::io::stdout().flush()?;
+            }
+            ProgressEvent::Other { text } => println!("\n{text}"),
+        },
+        ProgressFormat::Quiet => {
+            if let ProgressEvent::Other { text } = event {
+                println!("{text}");
+            }
+        }
+    }
+
+    Ok(())
+}
 
 #[derive(Parser, Debug)]
 #[command(author, about, long_about = "Run backups based on rsync")]
 struct Cli {
-    #[arg(short, long, help="The directory to backup from")]
-    source_dir: String,
-    #[arg(short, long, help="The directory to back to")]
-    target_dir: String,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Push source_dir -> target_dir (the original behaviour)
+    Backup(BackupArgs),
+    /// Pull a versioned snapshot back out of target_dir into an output directory
+    Restore(RestoreArgs),
+    /// List the versioned snapshots present in target_dir
+    List(ListArgs),
+}
+
+#[derive(Args, Debug)]
+struct BackupArgs {
+    #[arg(short, long, required_unless_present="config", help="The directory to backup from (ignored if --config is given)")]
+    source_dir: Option<String>,
+    #[arg(short, long, required_unless_present="config", help="The directory to back to (ignored if --config is given)")]
+    target_dir: Option<String>,
+    #[arg(long, help="Run every job defined in this TOML file instead of a single ad-hoc backup")]
+    config: Option<String>,
     #[arg(short='V', long, help="Create versioned backup.")]
     versioned: bool,
     #[arg(short, long, help="Exclude **Cache**")]
@@ -33,42 +102,167 @@ struct Cli {
     exclude_override: Option<String>,
     #[arg(short, long, help="Pass these args through to rsync")]
     pass_args: Option<String>,
+    #[arg(long, help="After a successful versioned backup, prune stale snapshots according to the --keep-* options")]
+    prune: bool,
+    #[arg(long, default_value_t=0, help="Always keep the N most recent snapshots")]
+    keep_last: usize,
+    #[arg(long, default_value_t=0, help="Keep one snapshot per day for the last N distinct days")]
+    keep_daily: usize,
+    #[arg(long, default_value_t=0, help="Keep one snapshot per ISO week for the last N distinct weeks")]
+    keep_weekly: usize,
+    #[arg(long, default_value_t=0, help="Keep one snapshot per calendar month for the last N distinct months")]
+    keep_monthly: usize,
+    #[arg(long, default_value_t=0, help="Keep one snapshot per calendar year for the last N distinct years")]
+    keep_yearly: usize,
+    #[arg(long, help="Print what --prune would remove without deleting anything")]
+    dry_run: bool,
+    #[arg(long, help="Emit the run summary (parsed rsync stats, timing, exit code) as JSON on stdout")]
+    json: bool,
+    #[arg(long, help="Kill rsync and fail if the whole run exceeds this many seconds")]
+    timeout: Option<u64>,
+    #[arg(long, help="Kill rsync and fail if no progress is seen for this many seconds")]
+    stall_timeout: Option<u64>,
+    #[arg(long, value_enum, default_value_t=ProgressFormat::Human, help="How to render per-file progress")]
+    progress_format: ProgressFormat,
 }
 
-
-
-async fn run_rsync(cli: &mut Cli) -> Result<ExitStatus> {
-    
-    let mut args: Vec<&str> = vec!["-axP", "--stats"];
-    
-    let mut other_args: Vec<String> = Vec::new();
-    if cli.versioned {
-        make_versioned_dir(cli, &mut other_args)?;
-        args.extend(other_args.iter().map(|x|x.as_str()));
-    }
-    if ! cli.no_exclude_caches {
-        args.extend(["--exclude", "**Cache**", "--exclude", "**cache**"]);
+impl BackupArgs {
+    fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: self.keep_last,
+            keep_daily: self.keep_daily,
+            keep_weekly: self.keep_weekly,
+            keep_monthly: self.keep_monthly,
+            keep_yearly: self.keep_yearly,
+        }
     }
+}
 
-    if let Some(exclude_override) = cli.exclude_override.as_deref() {
-        for excl in exclude_override.split(',') {
-            args.extend(["--exclude", excl]);
+impl From<Job> for BackupArgs {
+    fn from(job: Job) -> Self {
+        BackupArgs {
+            source_dir: Some(job.source_dir),
+            target_dir: Some(job.target_dir),
+            config: None,
+            versioned: job.versioned,
+            no_exclude_caches: job.no_exclude_caches,
+            exclude_override: job.exclude_override,
+            pass_args: job.pass_args,
+            prune: job.prune,
+            keep_last: job.keep_last,
+            keep_daily: job.keep_daily,
+            keep_weekly: job.keep_weekly,
+            keep_monthly: job.keep_monthly,
+            keep_yearly: job.keep_yearly,
+            dry_run: job.dry_run,
+            json: false,
+            timeout: None,
+            stall_timeout: None,
+            progress_format: ProgressFormat::Human,
         }
     }
+}
 
-    if let Some(pass_args) = cli.pass_args.as_deref() {
-        args.extend(pass_args.split(' '));
+#[derive(Args, Debug)]
+struct RestoreArgs {
+    #[arg(short, long, help="The versioned backup directory to restore from")]
+    target_dir: String,
+    #[arg(short, long, help="Snapshot to restore, as YYYYmmddHHMM (defaults to the newest snapshot)")]
+    at: Option<String>,
+    #[arg(short, long, help="The directory to restore into")]
+    output_dir: String,
+    #[arg(long, help="Emit the run summary (parsed rsync stats, timing, exit code) as JSON on stdout")]
+    json: bool,
+    #[arg(long, help="Kill rsync and fail if the whole run exceeds this many seconds")]
+    timeout: Option<u64>,
+    #[arg(long, help="Kill rsync and fail if no progress is seen for this many seconds")]
+    stall_timeout: Option<u64>,
+    #[arg(long, value_enum, default_value_t=ProgressFormat::Human, help="How to render per-file progress")]
+    progress_format: ProgressFormat,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[arg(short, long, help="The versioned backup directory to list snapshots of")]
+    target_dir: String,
+}
+
+/// A run was killed because it exceeded one of the `--timeout`/`--stall-timeout`
+/// budgets. Kept distinct from other `anyhow::Error`s so callers (and schedulers
+/// watching the process exit code) can tell a wedged rsync apart from any other
+/// failure.
+#[derive(Debug)]
+enum RsyncTimedOut {
+    Stalled { seconds: u64 },
+    TotalTimeout { seconds: u64 },
+}
+
+impl std::fmt::Display for RsyncTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RsyncTimedOut::Stalled { seconds } =>
+                write!(f, "rsync produced no progress for {seconds}s, killed"),
+            RsyncTimedOut::TotalTimeout { seconds } =>
+                write!(f, "rsync exceeded the {seconds}s total timeout, killed"),
+        }
     }
+}
+
+impl std::error::Error for RsyncTimedOut {}
+
+/// The distinct process exit code used when a run is killed by `--timeout`/`--stall-timeout`,
+/// so schedulers can tell a wedged rsync apart from a normal rsync failure.
+const TIMEOUT_EXIT_CODE: u8 = 124;
+
+/// Send SIGKILL to every process in `pgid`'s process group (rsync plus the
+/// sender/generator it forks), not just the single pid we spawned.
+fn kill_process_group(pgid: libc::pid_t) {
+    unsafe { libc::kill(-pgid, libc::SIGKILL); }
+}
 
-    args.push(&cli.source_dir);
-    args.push(&cli.target_dir);
+/// Run rsync from `source_dir` to `target_dir`, streaming its `-P` progress to stdout
+/// and parsing the `--stats` block it prints at the end into a [`BackupStats`].
+/// `extra_args` are inserted ahead of the source/target positional arguments, so
+/// callers can add things like `--exclude` or `--link-dest`.
+///
+/// If `stall_timeout` elapses with no new CR-delimited progress segment, or the
+/// whole run runs longer than `timeout`, rsync's whole process group is killed
+/// and an error carrying [`RsyncTimedOut`] is returned instead of waiting forever
+/// on a wedged network mount. rsync forks a second sender/generator process for
+/// the actual transfer, so killing only the top-level pid would leave that one
+/// running; putting rsync in its own process group lets us take both out at once.
+async fn run_rsync(
+    source_dir: &str,
+    target_dir: &str,
+    extra_args: &[String],
+    timeout: Option<Duration>,
+    stall_timeout: Option<Duration>,
+    progress_format: ProgressFormat,
+) -> Result<(ExitStatus, BackupStats)> {
+
+    let mut args: Vec<&str> = vec!["-axP", "--stats"];
+    args.extend(extra_args.iter().map(|x| x.as_str()));
+    args.push(source_dir);
+    args.push(target_dir);
 
     println!("Running rsync {}", args.join(" "));
 
-    let mut child = Command::new("rsync")
-        .args(args.as_slice())
-        .stdout(Stdio::piped())
-        .spawn()?;
+    let mut command = Command::new("rsync");
+    command.args(args.as_slice()).stdout(Stdio::piped());
+
+    // Start rsync in its own process group so a timeout kill can take out the
+    // sender/generator process it forks, not just the pid we spawned.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()?;
+    let pgid = child.id().ok_or_else(|| format_err!("rsync exited before we could read its pid"))? as libc::pid_t;
 
     let stdout = child.stdout.take()
         .expect("cannot read stdout");
@@ -77,18 +271,47 @@ async fn run_rsync(cli: &mut Cli) -> Result<ExitStatus> {
     // so we split on CR first. Look for newline later
     let mut reader = BufReader::new(stdout)
         .split(0x0d);
-    
-    let exit_status = tokio::spawn(async move {
-        let status = child.wait().await
-            .expect("process did not return status");
-
-        println!("child exit code was: {}", status.code().unwrap_or_default());
-        
-        status
-    });
-
-    
-    while let Some(segment) = reader.next_segment().await? {
+
+    let start = tokio::time::Instant::now();
+    let mut stats = BackupStats::default();
+
+    loop {
+        let remaining_total = timeout.map(|t| t.saturating_sub(start.elapsed()));
+        if remaining_total == Some(Duration::ZERO) {
+            kill_process_group(pgid);
+            child.wait().await?;
+            return Err(RsyncTimedOut::TotalTimeout { seconds: timeout.unwrap().as_secs() }.into());
+        }
+
+        let segment = match (stall_timeout, remaining_total) {
+            (None, None) => reader.next_segment().await?,
+            (stall, total) => {
+                let wait_for = match (stall, total) {
+                    (Some(s), Some(r)) => s.min(r),
+                    (Some(s), None) => s,
+                    (None, Some(r)) => r,
+                    (None, None) => unreachable!(),
+                };
+                match tokio::time::timeout(wait_for, reader.next_segment()).await {
+                    Ok(result) => result?,
+                    Err(_elapsed) => {
+                        kill_process_group(pgid);
+                        child.wait().await?;
+                        // wait_for was whichever deadline was nearer, so the one
+                        // that actually fired is the smaller of the two (total
+                        // wins ties, matching the unconditional check above).
+                        let total_expired = total.map_or(false, |r| r <= stall.unwrap_or(Duration::MAX));
+                        return Err(if total_expired {
+                            RsyncTimedOut::TotalTimeout { seconds: timeout.unwrap().as_secs() }
+                        } else {
+                            RsyncTimedOut::Stalled { seconds: stall.unwrap().as_secs() }
+                        }.into());
+                    }
+                }
+            }
+        };
+
+        let Some(segment) = segment else { break };
         if segment.is_empty() {
             break;
         }
@@ -96,48 +319,57 @@ async fn run_rsync(cli: &mut Cli) -> Result<ExitStatus> {
             .split(|x| *x==0x0au8)
             .collect::<Vec<&[u8]>>();
 
-        if parts.len() == 1 {
-            println!("Progress: {}", std::str::from_utf8(parts[0])?);
-        } else {
-            for p in parts {
-                let line = std::str::from_utf8(p)?;
-                println!("Line: {}", line);
+        for p in parts {
+            let line = std::str::from_utf8(p)?;
+            let event = progress::classify_line(line);
+            if let ProgressEvent::Other { text } = &event {
+                stats.accumulate_line(text);
             }
+            render_progress_event(&event, progress_format)?;
         }
     }
 
-    Ok(exit_status.await.unwrap())
+    let status = child.wait().await?;
+    println!("child exit code was: {}", status.code().unwrap_or_default());
+
+    Ok((status, stats))
 }
 
-fn dirname_is_valid_date(dir_entry: DirEntry) -> Result<String> {
+pub(crate) fn dirname_is_valid_date(dir_entry: DirEntry) -> Result<(String, DateTime<Local>)> {
     let dirname = dir_entry.file_name().into_string().unwrap();
 
-    ensure!(dir_entry.file_type().unwrap().is_dir(), 
+    ensure!(dir_entry.file_type().unwrap().is_dir(),
         format_err!("Directory entry {dirname} is not a directory"));
-    ensure!(Local.datetime_from_str(dirname.as_str(), "%Y%m%d%H%M").is_ok(), 
-        format_err!("Directory entry name {dirname} is not in datetime format of YYYYmmddHHMM"));
-    
-    Ok(dirname)
+    let when = Local.datetime_from_str(dirname.as_str(), "%Y%m%d%H%M")
+        .map_err(|_| format_err!("Directory entry name {dirname} is not in datetime format of YYYYmmddHHMM"))?;
+
+    Ok((dirname, when))
 }
 
-fn make_versioned_dir(cli: &mut Cli, other_args: &mut Vec<String>) -> Result<()>   {
+fn versioned_snapshots(target_dir: &str) -> Result<Vec<String>> {
+    let mut dirs: Vec<String> = read_dir(target_dir)?
+        .map(|d| dirname_is_valid_date(d?).map(|(dirname, _)| dirname))
+        .try_collect::<_>()?;
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn make_versioned_dir(cli: &mut BackupArgs, other_args: &mut Vec<String>) -> Result<()>   {
     // get latest backup in target dir
     // versioned dirs are datetime: YYYYmmddhhmm
-    let mut dirs: Vec<String> = read_dir(&cli.target_dir)?
-        .map(|d| dirname_is_valid_date(d?))
-        .try_collect::<_>()?;
-        
-   
-    //build target dir from date and time for versioned backups 
+    let target_dir = cli.target_dir.clone()
+        .ok_or_else(|| format_err!("--target-dir is required for a versioned backup"))?;
+    let mut dirs = versioned_snapshots(&target_dir)?;
+
+    //build target dir from date and time for versioned backups
     let now = Local::now();
     let now_dir = format!("{}", now.format("%Y%m%d%H%M"));
-    
-    let target_dir = cli.target_dir.to_owned();
-    cli.target_dir = Path::new(&target_dir)
-        .join(now_dir).to_string_lossy().to_string();
+
+    cli.target_dir = Some(Path::new(&target_dir)
+        .join(now_dir).to_string_lossy().to_string());
 
     if dirs.len() > 1 {
-        dirs.sort();
         other_args.push(
             format!("--link-dest={}", Path::new(&target_dir)
             .join(dirs.pop().unwrap())
@@ -147,15 +379,184 @@ fn make_versioned_dir(cli: &mut Cli, other_args: &mut Vec<String>) -> Result<()>
     Ok(())
 }
 
+/// Run a single backup job end-to-end: versioning, excludes, rsync, stats/metadata,
+/// and pruning. Used both for the ad-hoc single-job CLI invocation and for each
+/// job read out of a `--config` file.
+async fn run_backup_job(cli: &mut BackupArgs) -> Result<ExitStatus> {
+    let source_dir = cli.source_dir.clone()
+        .ok_or_else(|| format_err!("--source-dir is required"))?;
+
+    let mut args: Vec<String> = Vec::new();
+
+    if cli.versioned {
+        make_versioned_dir(cli, &mut args)?;
+    }
+    if ! cli.no_exclude_caches {
+        args.extend(["--exclude".to_string(), "**Cache**".to_string(), "--exclude".to_string(), "**cache**".to_string()]);
+    }
+
+    if let Some(exclude_override) = cli.exclude_override.as_deref() {
+        for excl in exclude_override.split(',') {
+            args.extend(["--exclude".to_string(), excl.to_string()]);
+        }
+    }
+
+    if let Some(pass_args) = cli.pass_args.as_deref() {
+        args.extend(pass_args.split(' ').map(str::to_string));
+    }
+
+    let target_dir = cli.target_dir.clone()
+        .ok_or_else(|| format_err!("--target-dir is required"))?;
+
+    let started_at = Local::now();
+    let (status, stats) = run_rsync(
+        &source_dir, &target_dir, &args,
+        cli.timeout.map(Duration::from_secs), cli.stall_timeout.map(Duration::from_secs),
+        cli.progress_format,
+    ).await?;
+    let ended_at = Local::now();
+
+    let metadata = RunMetadata {
+        started_at,
+        ended_at,
+        duration_seconds: (ended_at - started_at).num_milliseconds() as f64 / 1000.0,
+        exit_code: status.code(),
+        stats,
+    };
+
+    if cli.versioned {
+        let metadata_path = Path::new(&target_dir).join("metadata.json");
+        std::fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+    }
+
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&metadata)?);
+    }
+
+    if cli.versioned && cli.prune && status.success() {
+        let snapshot_dir = Path::new(&target_dir);
+        let snapshot_name = snapshot_dir.file_name()
+            .expect("versioned target dir has no final component")
+            .to_string_lossy().to_string();
+        let parent = snapshot_dir.parent()
+            .expect("versioned target dir has no parent");
+        prune_snapshots(parent, &cli.retention_policy(), &snapshot_name, cli.dry_run)?;
+    }
+
+    Ok(status)
+}
+
+/// Run every enabled job defined in `config_path` sequentially, printing a
+/// per-job success/failure table at the end. The process exits non-zero if
+/// any job failed, even though each job's own exit status is still reported.
+async fn run_backup_from_config(config_path: &str) -> Result<ExitCode> {
+    let jobs = load_jobs(Path::new(config_path))?;
+
+    let mut results: Vec<(String, Result<ExitStatus>)> = Vec::new();
+    for job in jobs {
+        if !job.enabled {
+            println!("Skipping disabled job '{}'", job.name);
+            continue;
+        }
+
+        let name = job.name.clone();
+        println!("=== Running job '{name}' ===");
+        let mut job_args: BackupArgs = job.into();
+        results.push((name, run_backup_job(&mut job_args).await));
+    }
+
+    println!();
+    let (job, status) = ("JOB", "STATUS");
+    println!("{job:<24}{status}");
+    let mut any_failed = false;
+    for (name, result) in &results {
+        let status = match result {
+            Ok(status) if status.success() => "ok".to_string(),
+            Ok(status) => { any_failed = true; format!("failed (exit {})", status.code().unwrap_or(-1)) }
+            Err(e) => { any_failed = true; format!("error: {e}") }
+        };
+        println!("{name:<24}{status}");
+    }
+
+    Ok(if any_failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+async fn run_backup(cli: &mut BackupArgs) -> Result<ExitCode> {
+    if let Some(config_path) = cli.config.clone() {
+        return run_backup_from_config(&config_path).await;
+    }
+
+    let status = run_backup_job(cli).await?;
+    Ok(ExitCode::from(status.code().unwrap() as u8))
+}
+
+async fn run_restore(args: &RestoreArgs) -> Result<ExitStatus> {
+    let snapshot = match args.at.as_deref() {
+        Some(at) => {
+            ensure!(Local.datetime_from_str(at, "%Y%m%d%H%M").is_ok(),
+                format_err!("--at value {at} is not in datetime format of YYYYmmddHHMM"));
+            at.to_string()
+        }
+        None => {
+            let mut dirs = versioned_snapshots(&args.target_dir)?;
+            dirs.pop().ok_or_else(|| format_err!("no versioned snapshots found in {}", args.target_dir))?
+        }
+    };
+
+    let snapshot_dir = Path::new(&args.target_dir).join(&snapshot);
+    println!("Restoring snapshot {snapshot} from {} into {}", snapshot_dir.display(), args.output_dir);
+
+    let started_at = Local::now();
+    let (status, stats) = run_rsync(
+        snapshot_dir.to_str().unwrap(), &args.output_dir, &[],
+        args.timeout.map(Duration::from_secs), args.stall_timeout.map(Duration::from_secs),
+        args.progress_format,
+    ).await?;
+    let ended_at = Local::now();
+
+    if args.json {
+        let metadata = RunMetadata {
+            started_at,
+            ended_at,
+            duration_seconds: (ended_at - started_at).num_milliseconds() as f64 / 1000.0,
+            exit_code: status.code(),
+            stats,
+        };
+        println!("{}", serde_json::to_string_pretty(&metadata)?);
+    }
+
+    Ok(status)
+}
+
+fn run_list(args: &ListArgs) -> Result<()> {
+    let dirs = versioned_snapshots(&args.target_dir)?;
+    for dir in dirs {
+        println!("{dir}");
+    }
+    Ok(())
+}
+
 
 #[tokio::main]
 async fn main() -> Result<ExitCode> {
     let mut cli = Cli::parse();
 
-    let status = run_rsync(&mut cli).await;
+    let result = match &mut cli.command {
+        Commands::Backup(args) => run_backup(args).await,
+        Commands::Restore(args) => run_restore(args).await
+            .map(|exit_status| ExitCode::from(exit_status.code().unwrap() as u8)),
+        Commands::List(args) => {
+            run_list(args)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+    };
 
-    match status {
-        Ok(exit_status) => Ok(ExitCode::from(exit_status.code().unwrap() as u8)),
+    match result {
+        Ok(exit_code) => Ok(exit_code),
+        Err(e) if e.downcast_ref::<RsyncTimedOut>().is_some() => {
+            eprintln!("{e}");
+            Ok(ExitCode::from(TIMEOUT_EXIT_CODE))
+        }
         Err(e) => Err(e)
     }
-}
\ No newline at end of file
+}
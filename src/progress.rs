@@ -0,0 +1,127 @@
+use serde::Serialize;
+
+/// A single `-P` progress update, parsed out of a line like
+/// `  1,234,567  45%   12.34MB/s    0:00:07`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub bytes: u64,
+    pub percent: u8,
+    pub rate_bytes_per_sec: f64,
+    pub eta: String,
+}
+
+/// What a single line of rsync `-P` output represents, once classified.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    FileStarted { name: String },
+    Progress(Progress),
+    Other { text: String },
+}
+
+pub fn classify_line(line: &str) -> ProgressEvent {
+    match parse_progress_line(line) {
+        Some(progress) => ProgressEvent::Progress(progress),
+        None if is_stats_line(line) => ProgressEvent::Other { text: line.to_string() },
+        None => ProgressEvent::FileStarted { name: line.to_string() },
+    }
+}
+
+fn is_stats_line(line: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "Number of files:", "Number of created files:", "Number of deleted files:",
+        "Number of regular files transferred:", "Total file size:",
+        "Total transferred file size:", "Literal data:", "Matched data:",
+        "File list size:", "File list generation time:", "File list transfer time:",
+    ];
+    line.is_empty()
+        || PREFIXES.iter().any(|p| line.starts_with(p))
+        || (line.starts_with("sent ") && line.contains("received "))
+        || line.starts_with("total size is ")
+}
+
+fn parse_progress_line(line: &str) -> Option<Progress> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 4 {
+        return None;
+    }
+
+    let bytes = parse_with_commas(tokens[0])?;
+    let percent = tokens[1].strip_suffix('%')?.parse().ok()?;
+    let rate_bytes_per_sec = parse_rate(tokens[2])?;
+    let eta = tokens[3].to_string();
+
+    Some(Progress { bytes, percent, rate_bytes_per_sec, eta })
+}
+
+// "1,234,567" -> 1234567
+fn parse_with_commas(s: &str) -> Option<u64> {
+    s.chars().filter(|c| *c != ',').collect::<String>().parse().ok()
+}
+
+// "12.34MB/s" or "874.00kB/s" -> bytes/sec. rsync uses a lowercase k for
+// kilobytes but uppercase M/G, so both cases need handling.
+fn parse_rate(s: &str) -> Option<f64> {
+    let s = s.strip_suffix("/s")?;
+    let unit_at = s.find(|c: char| c.is_ascii_alphabetic())?;
+    let (value, unit) = s.split_at(unit_at);
+    let value: f64 = value.parse().ok()?;
+    let multiplier = match unit {
+        "B" => 1.0,
+        "k" | "K" | "kB" | "KB" => 1024.0,
+        "M" | "MB" => 1024.0 * 1024.0,
+        "G" | "GB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some(value * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_a_progress_line() {
+        let event = classify_line("  1,234,567  45%   12.34MB/s    0:00:07");
+        let ProgressEvent::Progress(p) = event else { panic!("expected Progress, got {event:?}") };
+        assert_eq!(p.bytes, 1_234_567);
+        assert_eq!(p.percent, 45);
+        assert_eq!(p.rate_bytes_per_sec, 12.34 * 1024.0 * 1024.0);
+        assert_eq!(p.eta, "0:00:07");
+    }
+
+    #[test]
+    fn classifies_a_lowercase_kb_progress_line() {
+        let event = classify_line("  1,234,567  45%   874.00kB/s    0:00:07");
+        assert!(matches!(event, ProgressEvent::Progress(_)), "got {event:?}");
+    }
+
+    #[test]
+    fn classifies_a_stats_line_as_other() {
+        let event = classify_line("Number of files: 1,234");
+        assert!(matches!(event, ProgressEvent::Other { .. }));
+    }
+
+    #[test]
+    fn classifies_a_sent_received_summary_as_other() {
+        let event = classify_line("sent 12,456 bytes  received 789 bytes  4,417.00 bytes/sec");
+        assert!(matches!(event, ProgressEvent::Other { .. }));
+    }
+
+    #[test]
+    fn classifies_anything_else_as_a_filename() {
+        let event = classify_line("some/relative/path.txt");
+        assert!(matches!(event, ProgressEvent::FileStarted { name } if name == "some/relative/path.txt"));
+    }
+
+    #[test]
+    fn parse_rate_handles_all_units() {
+        assert_eq!(parse_rate("1.00B/s"), Some(1.0));
+        assert_eq!(parse_rate("1.00K/s"), Some(1024.0));
+        assert_eq!(parse_rate("1.00k/s"), Some(1024.0));
+        assert_eq!(parse_rate("1.00kB/s"), Some(1024.0));
+        assert_eq!(parse_rate("1.00MB/s"), Some(1024.0 * 1024.0));
+        assert_eq!(parse_rate("1.00GB/s"), Some(1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_rate("1.00TB/s"), None);
+    }
+}
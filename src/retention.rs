@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::prelude::*;
+
+/// Grandfather-father-son retention knobs, mirrored 1:1 onto CLI flags.
+///
+/// A snapshot survives pruning if it is selected by *any* of these classes
+/// (the union), so e.g. `--keep-daily 7 --keep-monthly 12` keeps the last
+/// 7 daily snapshots *and* the last 12 monthly ones, even where they overlap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    pub keep_last: usize,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub keep_monthly: usize,
+    pub keep_yearly: usize,
+}
+
+impl RetentionPolicy {
+    pub fn is_noop(&self) -> bool {
+        self.keep_last == 0
+            && self.keep_daily == 0
+            && self.keep_weekly == 0
+            && self.keep_monthly == 0
+            && self.keep_yearly == 0
+    }
+}
+
+/// A versioned snapshot directory paired with the timestamp parsed out of its name.
+struct Snapshot {
+    dirname: String,
+    when: DateTime<Local>,
+}
+
+/// Parse every child directory of `target_dir` into a [`Snapshot`], reusing
+/// `dirname_is_valid_date` so a stray non-conforming entry errors out here
+/// the same way it would for `restore`/`list`, instead of being silently skipped.
+fn collect_snapshots(target_dir: &Path) -> Result<Vec<Snapshot>> {
+    let mut snapshots: Vec<Snapshot> = fs::read_dir(target_dir)?
+        .map(|entry| {
+            let (dirname, when) = crate::dirname_is_valid_date(entry?)?;
+            Ok(Snapshot { dirname, when })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.when));
+    Ok(snapshots)
+}
+
+/// Decide which directory names out of `snapshots` should be kept under `policy`,
+/// always keeping `just_created` regardless of policy.
+fn snapshots_to_keep(snapshots: &[Snapshot], policy: &RetentionPolicy, just_created: &str) -> HashSet<String> {
+    let mut keep: HashSet<String> = HashSet::new();
+    keep.insert(just_created.to_string());
+
+    for snapshot in snapshots.iter().take(policy.keep_last) {
+        keep.insert(snapshot.dirname.clone());
+    }
+
+    let mut seen_days = HashSet::new();
+    for snapshot in snapshots {
+        if seen_days.len() >= policy.keep_daily {
+            break;
+        }
+        let day = snapshot.when.date_naive();
+        if seen_days.insert(day) {
+            keep.insert(snapshot.dirname.clone());
+        }
+    }
+
+    let mut seen_weeks = HashSet::new();
+    for snapshot in snapshots {
+        if seen_weeks.len() >= policy.keep_weekly {
+            break;
+        }
+        let iso = snapshot.when.iso_week();
+        let week = (iso.year(), iso.week());
+        if seen_weeks.insert(week) {
+            keep.insert(snapshot.dirname.clone());
+        }
+    }
+
+    let mut seen_months = HashSet::new();
+    for snapshot in snapshots {
+        if seen_months.len() >= policy.keep_monthly {
+            break;
+        }
+        let month = (snapshot.when.year(), snapshot.when.month());
+        if seen_months.insert(month) {
+            keep.insert(snapshot.dirname.clone());
+        }
+    }
+
+    let mut seen_years = HashSet::new();
+    for snapshot in snapshots {
+        if seen_years.len() >= policy.keep_yearly {
+            break;
+        }
+        let year = snapshot.when.year();
+        if seen_years.insert(year) {
+            keep.insert(snapshot.dirname.clone());
+        }
+    }
+
+    keep
+}
+
+/// Apply `policy` to the versioned snapshots under `target_dir`, deleting everything
+/// not selected by any retention class. `just_created` is always retained.
+/// If every `keep_*` count is 0, nothing is pruned at all.
+///
+/// With `dry_run` set, nothing is deleted; the snapshots that would be removed are
+/// printed instead. Deletions are irreversible even though `--link-dest` hardlink
+/// sharing makes them cheap on disk.
+pub fn prune_snapshots(target_dir: &Path, policy: &RetentionPolicy, just_created: &str, dry_run: bool) -> Result<()> {
+    if policy.is_noop() {
+        return Ok(());
+    }
+
+    let snapshots = collect_snapshots(target_dir)?;
+    let keep = snapshots_to_keep(&snapshots, policy, just_created);
+
+    for snapshot in &snapshots {
+        if keep.contains(&snapshot.dirname) {
+            continue;
+        }
+
+        let path = target_dir.join(&snapshot.dirname);
+        if dry_run {
+            println!("Would prune snapshot {}", path.display());
+        } else {
+            println!("Pruning snapshot {}", path.display());
+            fs::remove_dir_all(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(dirname: &str) -> Snapshot {
+        let when = Local.datetime_from_str(dirname, "%Y%m%d%H%M").unwrap();
+        Snapshot { dirname: dirname.to_string(), when }
+    }
+
+    #[test]
+    fn keeps_only_just_created_when_policy_is_noop() {
+        let snapshots = vec![snapshot("202501010000"), snapshot("202501020000")];
+        let policy = RetentionPolicy::default();
+        let keep = snapshots_to_keep(&snapshots, &policy, "202501030000");
+        assert_eq!(keep, HashSet::from(["202501030000".to_string()]));
+    }
+
+    #[test]
+    fn keep_last_selects_the_n_most_recent() {
+        // 2025-01-03, -02, -01 in descending order, as collect_snapshots produces.
+        let snapshots = vec![snapshot("202501030000"), snapshot("202501020000"), snapshot("202501010000")];
+        let policy = RetentionPolicy { keep_last: 2, ..Default::default() };
+        let keep = snapshots_to_keep(&snapshots, &policy, "202501030000");
+        assert!(keep.contains("202501030000"));
+        assert!(keep.contains("202501020000"));
+        assert!(!keep.contains("202501010000"));
+    }
+
+    #[test]
+    fn keep_weekly_spans_an_iso_week_year_boundary() {
+        // 2024-12-30 (Mon) is ISO week 1 of 2025; 2024-12-29 (Sun) is ISO week 52 of 2024.
+        let snapshots = vec![snapshot("202412300000"), snapshot("202412290000")];
+        let policy = RetentionPolicy { keep_weekly: 2, ..Default::default() };
+        let keep = snapshots_to_keep(&snapshots, &policy, "202412300000");
+        assert!(keep.contains("202412300000"));
+        assert!(keep.contains("202412290000"));
+    }
+
+    #[test]
+    fn keep_yearly_picks_one_snapshot_per_calendar_year() {
+        let snapshots = vec![snapshot("202501010000"), snapshot("202412310000"), snapshot("202401010000")];
+        let policy = RetentionPolicy { keep_yearly: 2, ..Default::default() };
+        let keep = snapshots_to_keep(&snapshots, &policy, "202501010000");
+        assert!(keep.contains("202501010000"));
+        assert!(keep.contains("202412310000"));
+        assert!(!keep.contains("202401010000"));
+    }
+}
@@ -0,0 +1,148 @@
+use chrono::prelude::*;
+use serde::Serialize;
+
+/// Counters parsed out of the `--stats` block rsync prints at the end of a run.
+/// Every field is optional because we only get the localized, English-language
+/// wording we look for here; anything we fail to recognise is left as `None`
+/// rather than failing the whole backup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BackupStats {
+    pub num_files: Option<u64>,
+    pub num_regular_files_transferred: Option<u64>,
+    pub total_file_size: Option<u64>,
+    pub total_transferred_file_size: Option<u64>,
+    pub literal_data: Option<u64>,
+    pub sent_bytes: Option<u64>,
+    pub received_bytes: Option<u64>,
+    pub total_size: Option<u64>,
+    pub speedup: Option<f64>,
+}
+
+impl BackupStats {
+    /// Feed a single line of rsync output in; recognised `--stats` lines update
+    /// the matching field, anything else is ignored.
+    pub fn accumulate_line(&mut self, line: &str) {
+        let line = line.trim();
+
+        if let Some(rest) = line.strip_prefix("Number of files:") {
+            self.num_files = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Number of regular files transferred:") {
+            self.num_regular_files_transferred = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Total file size:") {
+            self.total_file_size = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Total transferred file size:") {
+            self.total_transferred_file_size = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Literal data:") {
+            self.literal_data = first_number(rest);
+        } else if line.starts_with("sent ") && line.contains("received ") {
+            let mut numbers = numbers_in(line).into_iter();
+            self.sent_bytes = numbers.next();
+            self.received_bytes = numbers.next();
+        } else if let Some(rest) = line.strip_prefix("total size is ") {
+            let (total, speedup) = rest.split_once("speedup is")
+                .unwrap_or((rest, ""));
+            self.total_size = first_number(total);
+            self.speedup = speedup.trim().trim_end_matches(|c: char| !c.is_ascii_digit() && c != '.')
+                .parse::<f64>().ok();
+        }
+    }
+}
+
+/// Parse the run of digits (allowing thousands separators) at the start of the
+/// trimmed `s` as an integer, e.g. `" 1,234,567 bytes"` -> `Some(1234567)`.
+/// Digits that come after leading non-digit text are not searched for.
+fn first_number(s: &str) -> Option<u64> {
+    let digits: String = s.trim().chars()
+        .take_while(|c| c.is_ascii_digit() || *c == ',')
+        .filter(|c| *c != ',')
+        .collect();
+    if digits.is_empty() { None } else { digits.parse().ok() }
+}
+
+/// Extract every standalone number from a line such as
+/// `sent 12,456 bytes  received 789 bytes  4,417.00 bytes/sec`.
+fn numbers_in(s: &str) -> Vec<u64> {
+    s.split_whitespace().filter_map(first_number).collect()
+}
+
+/// Everything we know about a single completed rsync run, written out as
+/// `metadata.json` inside each versioned snapshot directory and emitted on stdout
+/// for `--json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMetadata {
+    pub started_at: DateTime<Local>,
+    pub ended_at: DateTime<Local>,
+    pub duration_seconds: f64,
+    pub exit_code: Option<i32>,
+    pub stats: BackupStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_number_strips_commas_and_trailing_words() {
+        assert_eq!(first_number(" 1,234,567 bytes"), Some(1_234_567));
+        assert_eq!(first_number("42"), Some(42));
+        assert_eq!(first_number("no digits here"), None);
+    }
+
+    #[test]
+    fn numbers_in_extracts_every_token() {
+        assert_eq!(
+            numbers_in("sent 12,456 bytes  received 789 bytes  4,417.00 bytes/sec"),
+            vec![12_456, 789, 4_417]
+        );
+    }
+
+    #[test]
+    fn accumulates_simple_count_lines() {
+        let mut stats = BackupStats::default();
+        stats.accumulate_line("Number of files: 1,234");
+        stats.accumulate_line("Number of regular files transferred: 56");
+        stats.accumulate_line("Total file size: 789,000");
+        stats.accumulate_line("Total transferred file size: 12,000");
+        stats.accumulate_line("Literal data: 3,000");
+
+        assert_eq!(stats.num_files, Some(1_234));
+        assert_eq!(stats.num_regular_files_transferred, Some(56));
+        assert_eq!(stats.total_file_size, Some(789_000));
+        assert_eq!(stats.total_transferred_file_size, Some(12_000));
+        assert_eq!(stats.literal_data, Some(3_000));
+    }
+
+    #[test]
+    fn accumulates_sent_received_line() {
+        let mut stats = BackupStats::default();
+        stats.accumulate_line("sent 12,456 bytes  received 789 bytes  4,417.00 bytes/sec");
+
+        assert_eq!(stats.sent_bytes, Some(12_456));
+        assert_eq!(stats.received_bytes, Some(789));
+    }
+
+    #[test]
+    fn accumulates_total_size_and_speedup() {
+        let mut stats = BackupStats::default();
+        stats.accumulate_line("total size is 1,234,567  speedup is 3.45");
+
+        assert_eq!(stats.total_size, Some(1_234_567));
+        assert_eq!(stats.speedup, Some(3.45));
+    }
+
+    #[test]
+    fn total_size_without_a_speedup_clause_leaves_speedup_none() {
+        let mut stats = BackupStats::default();
+        stats.accumulate_line("total size is 1,234,567");
+
+        assert_eq!(stats.total_size, Some(1_234_567));
+        assert_eq!(stats.speedup, None);
+    }
+
+    #[test]
+    fn unrecognised_lines_are_ignored() {
+        let mut stats = BackupStats::default();
+        stats.accumulate_line("building file list ...");
+        assert_eq!(stats, BackupStats::default());
+    }
+}